@@ -0,0 +1,10 @@
+extern "C" {
+	#[link_name = "revert"]
+	fn revert_import(ptr: *const u8, len: u32) -> !;
+}
+
+/// Finalize the contract's execution with a revert status and a `reason`
+/// payload, instead of a successful return.
+pub fn revert(reason: &[u8]) -> ! {
+	unsafe { revert_import(reason.as_ptr(), reason.len() as u32) }
+}
@@ -1,4 +1,5 @@
 use core::{slice, ops};
+use alloc::vec::Vec;
 use ext;
 
 #[repr(C)]
@@ -60,10 +61,565 @@ impl AsRef<[u8]> for WrappedArgs {
 	}
 }
 
+impl WrappedArgs {
+	/// Decode the input bytes as `T`.
+	///
+	/// # Examples
+	///
+	/// ```rust,no_run
+	/// # use pwasm_std::parse_args;
+	/// # let input = unsafe { parse_args(::std::ptr::null_mut()).0 };
+	/// let (a, b): (u32, Vec<u8>) = input.decode().unwrap();
+	/// # let _ = (a, b);
+	/// ```
+	pub fn decode<T: Decode>(&self) -> Result<T, DecodeError> {
+		let mut reader = Reader::new(&*self);
+		T::decode(&mut reader)
+	}
+
+	/// Split this input into its 4-byte function selector and the
+	/// remaining argument bytes, for Ethereum ABI-style dispatch.
+	///
+	/// Returns `None` when the input is shorter than 4 bytes, in which
+	/// case the caller should route the call to its `fallback` rather
+	/// than decode further. See [`read_selector`].
+	///
+	/// [`read_selector`]: fn.read_selector.html
+	pub fn selector(&self) -> Option<(Selector, &[u8])> {
+		read_selector(&*self)
+	}
+}
+
+/// An Ethereum ABI-style 4-byte function selector, conventionally the
+/// first 4 bytes of the keccak256 hash of a method's canonical signature.
+pub type Selector = [u8; 4];
+
+/// Splits the leading 4-byte function selector off of `input`.
+///
+/// Returns `None` when `input` is shorter than 4 bytes; callers should
+/// route the call to a `fallback` path in that case instead of decoding
+/// further.
+///
+/// This is the primitive [`abi_dispatch!`] uses to split off the selector
+/// it then compares against [`selector`] of each arm's signature.
+///
+/// [`abi_dispatch!`]: macro.abi_dispatch.html
+pub fn read_selector(input: &[u8]) -> Option<(Selector, &[u8])> {
+	if input.len() < 4 {
+		return None;
+	}
+	let mut selector = [0u8; 4];
+	selector.copy_from_slice(&input[..4]);
+	Some((selector, &input[4..]))
+}
+
+/// Computes the 4-byte selector of a method's canonical signature (e.g.
+/// `"transfer(address,uint32)"`), matching the EVM ABI convention: the
+/// first 4 bytes of the keccak256 hash of the signature string.
+pub fn selector(signature: &str) -> Selector {
+	let digest = keccak256(signature.as_bytes());
+	let mut out = [0u8; 4];
+	out.copy_from_slice(&digest[..4]);
+	out
+}
+
+const KECCAK_RATE: usize = 136;
+
+const KECCAK_RNDC: [u64; 24] = [
+	0x0000000000000001, 0x0000000000008082, 0x800000000000808a, 0x8000000080008000,
+	0x000000000000808b, 0x0000000080000001, 0x8000000080008081, 0x8000000000008009,
+	0x000000000000008a, 0x0000000000000088, 0x0000000080008009, 0x000000008000000a,
+	0x000000008000808b, 0x800000000000008b, 0x8000000000008089, 0x8000000000008003,
+	0x8000000000008002, 0x8000000000000080, 0x000000000000800a, 0x800000008000000a,
+	0x8000000080008081, 0x8000000000008080, 0x0000000080000001, 0x8000000080008008,
+];
+
+const KECCAK_ROTC: [u32; 24] = [
+	1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44,
+];
+
+const KECCAK_PILN: [usize; 24] = [
+	10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1,
+];
+
+fn keccak_f(st: &mut [u64; 25]) {
+	for &rndc in KECCAK_RNDC.iter() {
+		let mut bc = [0u64; 5];
+		for i in 0..5 {
+			bc[i] = st[i] ^ st[i + 5] ^ st[i + 10] ^ st[i + 15] ^ st[i + 20];
+		}
+		for i in 0..5 {
+			let t = bc[(i + 4) % 5] ^ bc[(i + 1) % 5].rotate_left(1);
+			let mut j = i;
+			while j < 25 {
+				st[j] ^= t;
+				j += 5;
+			}
+		}
+
+		let mut t = st[1];
+		for i in 0..24 {
+			let j = KECCAK_PILN[i];
+			let tmp = st[j];
+			st[j] = t.rotate_left(KECCAK_ROTC[i]);
+			t = tmp;
+		}
+
+		let mut j = 0;
+		while j < 25 {
+			let mut bc = [0u64; 5];
+			bc.copy_from_slice(&st[j..j + 5]);
+			for i in 0..5 {
+				st[j + i] ^= !bc[(i + 1) % 5] & bc[(i + 2) % 5];
+			}
+			j += 5;
+		}
+
+		st[0] ^= rndc;
+	}
+}
+
+fn keccak_absorb(st: &mut [u64; 25], block: &[u8]) {
+	for (i, word) in block.chunks_exact(8).enumerate() {
+		let mut buf = [0u8; 8];
+		buf.copy_from_slice(word);
+		st[i] ^= u64::from_le_bytes(buf);
+	}
+}
+
+/// Keccak-256 (the original Keccak padding, as used by Ethereum's ABI
+/// selectors) of `input`.
+fn keccak256(input: &[u8]) -> [u8; 32] {
+	let mut st = [0u64; 25];
+
+	let mut chunks = input.chunks_exact(KECCAK_RATE);
+	for chunk in &mut chunks {
+		keccak_absorb(&mut st, chunk);
+		keccak_f(&mut st);
+	}
+
+	let rest = chunks.remainder();
+	let mut block = [0u8; KECCAK_RATE];
+	block[..rest.len()].copy_from_slice(rest);
+	block[rest.len()] ^= 0x01;
+	block[KECCAK_RATE - 1] ^= 0x80;
+	keccak_absorb(&mut st, &block);
+	keccak_f(&mut st);
+
+	let mut out = [0u8; 32];
+	for (i, word) in st[..4].iter().enumerate() {
+		out[i * 8..i * 8 + 8].copy_from_slice(&word.to_le_bytes());
+	}
+	out
+}
+
+/// Generates a `call` entry point that dispatches Ethereum ABI-style
+/// contract calls by method selector.
+///
+/// For each `"signature" => method` arm, the generated `call` reads the
+/// leading 4-byte selector off the input (via [`WrappedArgs::selector`])
+/// and compares it against [`selector`] of each arm's signature, in
+/// order, until one matches; on a match it [`Decode`]s the remaining
+/// bytes into `method`'s argument tuple, invokes `method`, [`Encode`]s
+/// its return value, and finalizes it with [`WrappedResult::done`]. A
+/// selector that matches no arm, input shorter than 4 bytes, or an
+/// argument tuple that fails to decode, is routed to `$fallback` instead
+/// of aborting.
+///
+/// Note this recomputes `selector($sig)` - a keccak256 hash - for every
+/// candidate arm on every call, rather than comparing against a table
+/// built once; a contract with many exposed methods pays for up to that
+/// many hashes per dispatch. Worth a precomputed static table if that
+/// ever shows up in profiling.
+///
+/// Also note this is a `macro_rules!` list of arms (`abi_dispatch! {
+/// fallback: f, "sig" => method, ... }`): each exposed method and its
+/// canonical signature are listed by hand; nothing derives them from a
+/// trait.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # #[macro_use] extern crate pwasm_std;
+/// # use pwasm_std::{WrappedArgs, WrappedResult};
+/// fn add(args: (u32, u32)) -> u32 {
+/// 	args.0 + args.1
+/// }
+///
+/// fn fallback(_input: WrappedArgs, result: WrappedResult) {
+/// 	result.revert("unknown selector");
+/// }
+///
+/// abi_dispatch! {
+/// 	fallback: fallback,
+/// 	"add(uint32,uint32)" => add,
+/// }
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! abi_dispatch {
+	(fallback: $fallback:path, $( $sig:expr => $method:path ),+ $(,)?) => {
+		#[no_mangle]
+		pub fn call(descriptor: *mut u8) {
+			let (input, result) = unsafe { $crate::parse_args(descriptor) };
+			match input.selector() {
+				Some((found, rest)) => {
+					$(
+						if found == $crate::selector($sig) {
+							let mut reader = $crate::Reader::new(rest);
+							return match $crate::Decode::decode(&mut reader) {
+								Ok(args) => {
+									let ret = $method(args);
+									let mut writer = $crate::Writer::new();
+									$crate::Encode::encode(&ret, &mut writer);
+									result.done(writer)
+								}
+								Err(_) => $fallback(input, result),
+							};
+						}
+					)+
+					$fallback(input, result)
+				}
+				None => $fallback(input, result),
+			}
+		}
+	};
+}
+
+#[cfg(test)]
+mod selector_tests {
+	use super::*;
+
+	#[test]
+	fn keccak256_of_empty_input() {
+		assert_eq!(
+			keccak256(&[]),
+			[
+				0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc,
+				0xc7, 0x03, 0xc0, 0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa,
+				0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70,
+			]
+		);
+	}
+
+	#[test]
+	fn keccak256_of_abc() {
+		assert_eq!(
+			keccak256(b"abc"),
+			[
+				0x4e, 0x03, 0x65, 0x7a, 0xea, 0x45, 0xa9, 0x4f, 0xc7, 0xd4, 0x7b, 0xa8, 0x26,
+				0xc8, 0xd6, 0x67, 0xc0, 0xd1, 0xe6, 0xe3, 0x3a, 0x64, 0xa0, 0x36, 0xec, 0x44,
+				0xf5, 0x8f, 0xa1, 0x2d, 0x6c, 0x45,
+			]
+		);
+	}
+
+	#[test]
+	fn selector_is_first_four_bytes_of_keccak256() {
+		let sig = "baz(uint32,bool)";
+		let digest = keccak256(sig.as_bytes());
+		assert_eq!(selector(sig), [digest[0], digest[1], digest[2], digest[3]]);
+	}
+
+	#[test]
+	fn read_selector_rejects_short_input() {
+		assert_eq!(read_selector(&[1, 2, 3]), None);
+	}
+
+	#[test]
+	fn read_selector_splits_selector_and_rest() {
+		assert_eq!(read_selector(&[1, 2, 3, 4, 5, 6]), Some(([1, 2, 3, 4], &[5u8, 6][..])));
+	}
+}
+
+/// Error that can occur while decoding a value out of a [`Reader`].
+///
+/// [`Reader`]: struct.Reader.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+	/// The input didn't contain enough bytes to decode the requested value.
+	UnexpectedEof,
+}
+
+/// A cursor over a byte slice that decodes typed values in sequence.
+///
+/// Every read advances an internal offset and is bounds-checked against
+/// the underlying slice; reading past the end yields [`DecodeError::UnexpectedEof`]
+/// rather than panicking.
+///
+/// [`DecodeError::UnexpectedEof`]: enum.DecodeError.html
+pub struct Reader<'a> {
+	data: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Reader<'a> {
+	/// Create a reader over `data`, starting at offset `0`.
+	pub fn new(data: &'a [u8]) -> Self {
+		Reader { data: data, pos: 0 }
+	}
+
+	/// Number of bytes left to read.
+	pub fn remaining(&self) -> usize {
+		self.data.len() - self.pos
+	}
+
+	fn read_slice(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+		if self.remaining() < len {
+			return Err(DecodeError::UnexpectedEof);
+		}
+		let slice = &self.data[self.pos..self.pos + len];
+		self.pos += len;
+		Ok(slice)
+	}
+
+	/// Read a single byte.
+	pub fn read_u8(&mut self) -> Result<u8, DecodeError> {
+		Ok(self.read_slice(1)?[0])
+	}
+
+	/// Read a little-endian `u32`.
+	pub fn read_u32(&mut self) -> Result<u32, DecodeError> {
+		Ok(u32::from_le_bytes(self.read_fixed::<4>()?))
+	}
+
+	/// Read a little-endian `u64`.
+	pub fn read_u64(&mut self) -> Result<u64, DecodeError> {
+		Ok(u64::from_le_bytes(self.read_fixed::<8>()?))
+	}
+
+	/// Read exactly `N` bytes.
+	pub fn read_fixed<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+		let mut out = [0u8; N];
+		out.copy_from_slice(self.read_slice(N)?);
+		Ok(out)
+	}
+
+	/// Read a length-prefixed byte vector: a little-endian `u32` length
+	/// followed by that many bytes.
+	pub fn read_bytes(&mut self) -> Result<Vec<u8>, DecodeError> {
+		let len = self.read_u32()? as usize;
+		Ok(self.read_slice(len)?.to_vec())
+	}
+}
+
+/// Accumulates encoded bytes to be handed to [`WrappedResult::done`].
+///
+/// [`WrappedResult::done`]: struct.WrappedResult.html#method.done
+pub struct Writer {
+	buf: Vec<u8>,
+}
+
+impl Writer {
+	/// Create an empty writer.
+	pub fn new() -> Self {
+		Writer { buf: Vec::new() }
+	}
+
+	/// Append raw, unprefixed bytes.
+	pub fn write_raw(&mut self, bytes: &[u8]) {
+		self.buf.extend_from_slice(bytes);
+	}
+
+	/// Write a single byte.
+	pub fn write_u8(&mut self, val: u8) {
+		self.buf.push(val);
+	}
+
+	/// Write a little-endian `u32`.
+	pub fn write_u32(&mut self, val: u32) {
+		self.write_raw(&val.to_le_bytes());
+	}
+
+	/// Write a little-endian `u64`.
+	pub fn write_u64(&mut self, val: u64) {
+		self.write_raw(&val.to_le_bytes());
+	}
+
+	/// Write a byte slice prefixed with its little-endian `u32` length.
+	pub fn write_bytes(&mut self, bytes: &[u8]) {
+		self.write_u32(bytes.len() as u32);
+		self.write_raw(bytes);
+	}
+
+	/// Consume the writer, returning the accumulated bytes.
+	pub fn into_vec(self) -> Vec<u8> {
+		self.buf
+	}
+}
+
+impl AsRef<[u8]> for Writer {
+	fn as_ref(&self) -> &[u8] {
+		&self.buf
+	}
+}
+
+/// A value that can be decoded from a [`Reader`].
+///
+/// [`Reader`]: struct.Reader.html
+pub trait Decode: Sized {
+	/// Decode `Self` from `reader`.
+	fn decode(reader: &mut Reader) -> Result<Self, DecodeError>;
+}
+
+/// A value that can be encoded into a [`Writer`].
+///
+/// [`Writer`]: struct.Writer.html
+pub trait Encode {
+	/// Encode `self` into `writer`.
+	fn encode(&self, writer: &mut Writer);
+}
+
+macro_rules! impl_codec_for_int {
+	($ty:ty, $read:ident, $write:ident) => {
+		impl Decode for $ty {
+			fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+				reader.$read()
+			}
+		}
+
+		impl Encode for $ty {
+			fn encode(&self, writer: &mut Writer) {
+				writer.$write(*self)
+			}
+		}
+	}
+}
+
+impl Decode for u8 {
+	fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+		reader.read_u8()
+	}
+}
+
+impl Encode for u8 {
+	fn encode(&self, writer: &mut Writer) {
+		writer.write_u8(*self)
+	}
+}
+
+impl_codec_for_int!(u32, read_u32, write_u32);
+impl_codec_for_int!(u64, read_u64, write_u64);
+
+impl Decode for Vec<u8> {
+	fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+		reader.read_bytes()
+	}
+}
+
+impl Encode for Vec<u8> {
+	fn encode(&self, writer: &mut Writer) {
+		writer.write_bytes(self)
+	}
+}
+
+impl<const N: usize> Decode for [u8; N] {
+	fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+		reader.read_fixed::<N>()
+	}
+}
+
+impl<const N: usize> Encode for [u8; N] {
+	fn encode(&self, writer: &mut Writer) {
+		writer.write_raw(&self[..])
+	}
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+	fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+		Ok((A::decode(reader)?, B::decode(reader)?))
+	}
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+	fn encode(&self, writer: &mut Writer) {
+		self.0.encode(writer);
+		self.1.encode(writer);
+	}
+}
+
+impl<A: Decode, B: Decode, C: Decode> Decode for (A, B, C) {
+	fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+		Ok((A::decode(reader)?, B::decode(reader)?, C::decode(reader)?))
+	}
+}
+
+impl<A: Encode, B: Encode, C: Encode> Encode for (A, B, C) {
+	fn encode(&self, writer: &mut Writer) {
+		self.0.encode(writer);
+		self.1.encode(writer);
+		self.2.encode(writer);
+	}
+}
+
+#[cfg(test)]
+mod codec_tests {
+	use super::*;
+	use alloc::vec;
+
+	#[test]
+	fn read_u32_past_end_is_unexpected_eof() {
+		let mut reader = Reader::new(&[1, 2, 3]);
+		assert_eq!(reader.read_u32(), Err(DecodeError::UnexpectedEof));
+	}
+
+	#[test]
+	fn read_bytes_round_trips_through_writer() {
+		let mut writer = Writer::new();
+		writer.write_bytes(&[1, 2, 3, 4, 5]);
+
+		let mut reader = Reader::new(writer.as_ref());
+		assert_eq!(reader.read_bytes(), Ok(vec![1, 2, 3, 4, 5]));
+		assert_eq!(reader.remaining(), 0);
+	}
+
+	#[test]
+	fn read_bytes_with_truncated_payload_is_unexpected_eof() {
+		let mut writer = Writer::new();
+		writer.write_bytes(&[1, 2, 3, 4, 5]);
+		let mut truncated = writer.into_vec();
+		// Keep the whole 4-byte length prefix but only 3 of the 5 payload bytes.
+		truncated.truncate(7);
+
+		let mut reader = Reader::new(&truncated);
+		assert_eq!(reader.read_bytes(), Err(DecodeError::UnexpectedEof));
+	}
+
+	#[test]
+	fn read_fixed_reads_exactly_n_bytes() {
+		let mut reader = Reader::new(&[0xde, 0xad, 0xbe, 0xef, 0xff]);
+		assert_eq!(reader.read_fixed::<4>(), Ok([0xde, 0xad, 0xbe, 0xef]));
+		assert_eq!(reader.remaining(), 1);
+	}
+
+	#[test]
+	fn tuple_encode_decode_round_trips() {
+		let mut writer = Writer::new();
+		(7u32, vec![9u8, 8, 7]).encode(&mut writer);
+
+		let mut reader = Reader::new(writer.as_ref());
+		let decoded: (u32, Vec<u8>) = Decode::decode(&mut reader).unwrap();
+		assert_eq!(decoded, (7u32, vec![9u8, 8, 7]));
+	}
+}
+
 /// Writeable handle of execution results.
 ///
 /// You can use this handle to write execution results of your contract.
-pub struct WrappedResult;
+pub struct WrappedResult {
+	desc: *const Descriptor,
+	reserved_len: Option<usize>,
+}
+
+/// Error returned when an encoded result doesn't fit into the result
+/// buffer declared by the `Descriptor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteError {
+	/// Number of bytes the descriptor reserved for the result.
+	pub capacity: usize,
+	/// Number of bytes the caller attempted to write.
+	pub len: usize,
+}
 
 impl WrappedResult {
 	/// Finalize writing result into the descriptor
@@ -90,6 +646,86 @@ impl WrappedResult {
 		ext::return_(result);
 		// Control flow can't get here so `val` doesn't get dropped.
 	}
+
+	/// Finalize execution with a revert status and an accompanying reason,
+	/// instead of a successful return.
+	///
+	/// Use this to signal failure to the caller (e.g. a failed
+	/// precondition) without trapping the whole execution.
+	///
+	/// # Examples
+	///
+	/// ```rust,no_run
+	/// # use pwasm_std::parse_args;
+	/// # let result = unsafe { parse_args(::std::ptr::null_mut()).1 };
+	/// result.revert("insufficient balance");
+	/// ```
+	pub fn revert<T: AsRef<[u8]>>(self, reason: T) -> ! {
+		let reason = reason.as_ref();
+		ext::revert(reason);
+		// Control flow can't get here so `reason` doesn't get dropped.
+	}
+
+	/// Like [`done`](#method.done), but checks the result capacity declared
+	/// by the `Descriptor` (`result_len`) before writing, returning
+	/// [`WriteError`] instead of silently truncating or trapping when the
+	/// encoded result is too large.
+	pub fn try_done<T: AsRef<[u8]>>(self, val: T) -> Result<(), WriteError> {
+		let result = val.as_ref();
+		let capacity = unsafe { (*self.desc).result_len };
+		if result.len() > capacity {
+			return Err(WriteError { capacity: capacity, len: result.len() });
+		}
+		ext::return_(result);
+	}
+
+	/// Reserve `len` bytes directly in the descriptor's result buffer, when
+	/// the host has pre-provisioned one (`result_ptr` non-null) and it's
+	/// large enough (`len <= result_len`).
+	///
+	/// This lets a contract serialize its result in place and then call
+	/// [`commit`](#method.commit), avoiding the intermediate allocation and
+	/// copy that [`done`](#method.done) performs. Returns `None` when no
+	/// region was provided or it's too small, in which case fall back to
+	/// `done`.
+	///
+	/// Takes `&mut self` because the returned slice aliases the
+	/// descriptor's result region; borrowing mutably rules out calling
+	/// `reserve` again while the first slice is still live.
+	pub fn reserve(&mut self, len: usize) -> Option<&mut [u8]> {
+		unsafe {
+			let desc = &*self.desc;
+			if desc.result_ptr.is_null() || len > desc.result_len {
+				return None;
+			}
+			self.reserved_len = Some(len);
+			Some(slice::from_raw_parts_mut(desc.result_ptr as *mut u8, len))
+		}
+	}
+
+	/// Finalize a result previously written in place via
+	/// [`reserve`](#method.reserve).
+	///
+	/// `written` is the number of bytes actually written into the
+	/// reserved slice, and becomes the contract's return value.
+	///
+	/// # Panics
+	///
+	/// Panics if `reserve` was never called, or if `written` exceeds the
+	/// length that was reserved.
+	pub fn commit(self, written: usize) -> ! {
+		let reserved_len = self.reserved_len.expect("commit called without a prior reserve");
+		assert!(
+			written <= reserved_len,
+			"commit called with written ({}) greater than reserved length ({})",
+			written,
+			reserved_len,
+		);
+		unsafe {
+			let ptr = (*self.desc).result_ptr;
+			ext::return_(slice::from_raw_parts(ptr, written));
+		}
+	}
 }
 
 /// Parse decriptor into wrapped args and result.
@@ -112,6 +748,6 @@ impl WrappedResult {
 pub unsafe fn parse_args(ptr: *mut u8) -> (WrappedArgs, WrappedResult) {
 	let desc = ptr as *mut Descriptor;
 	let args = WrappedArgs { desc: desc };
-	let result = WrappedResult;
+	let result = WrappedResult { desc: desc, reserved_len: None };
 	(args, result)
 }